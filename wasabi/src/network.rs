@@ -1,28 +1,55 @@
 use bytes;
+use libc;
 use mio;
 use mio::net;
+use mio_uds;
 use slab::Slab;
-use std::io::{Error, ErrorKind, Read, Result, Write};
+use socket2;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Read, Result, Write};
 use std::net::Shutdown;
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::result;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 use std::time;
 
 #[derive(Debug)]
-enum NetTcp {
+enum NetSocket {
     Listener(mio::net::TcpListener),
     Stream(mio::net::TcpStream),
+    Datagram(mio::net::UdpSocket),
+    // PendingSocket is an unbound, unregistered socket created by
+    // tcp_socket_v4/tcp_socket_v6 so pre-bind options (SO_REUSEADDR,
+    // SO_REUSEPORT, TCP_NODELAY, TTL) can be applied before bind()/listen()
+    // or connect() transitions it into a Listener/Stream and registers it.
+    PendingSocket(socket2::Socket),
+    UnixListener(mio_uds::UnixListener),
+    UnixStream(mio_uds::UnixStream),
 }
 
+// WAKER_TOKEN is reserved for the poll-interrupt registration so it never
+// collides with a slab-assigned token (slab ids start at 0 and grow).
+// mio 0.6's Poll itself reserves Token(usize::MAX) internally (AWAKEN), so
+// registering on that exact token panics with "invalid token" - back off by
+// one.
+const WAKER_TOKEN: usize = usize::max_value() - 1;
+
 #[derive(Debug)]
 pub struct NetLoop {
-    slab: Slab<NetTcp>,
+    slab: Slab<NetSocket>,
     poll: Arc<mio::Poll>,
     pub is_listening: bool,
     event_receiver: mpsc::Receiver<mio::event::Event>,
+    waker_registration: mio::Registration,
+    waker_set_readiness: mio::SetReadiness,
+    shutdown: Arc<AtomicBool>,
+    thread_handle: Option<thread::JoinHandle<()>>,
+    interests: HashMap<usize, mio::Ready>,
 }
 
 pub fn event_to_ints(event: &mio::Event) -> ((i64, i64)) {
@@ -44,28 +71,117 @@ pub fn event_to_ints(event: &mio::Event) -> ((i64, i64)) {
     (event.token().0 as i64, state)
 }
 
+// addr_to_bytes writes a self-describing, tagged encoding of `addr` into `b`:
+// a leading family byte (4 or 6), followed by the address octets, a 2-byte
+// LE port, and for v6 the 4-byte LE flowinfo and scope_id. This lets
+// `bytes_to_addr` reconstruct the correct variant without out-of-band
+// knowledge of which family produced the bytes.
 pub fn addr_to_bytes(addr: SocketAddr, b: &mut [u8]) -> Result<()> {
     match addr {
         SocketAddr::V4(a) => {
-            b[0..4].copy_from_slice(&a.ip().octets());
-            b[4..6].copy_from_slice(&bytes::u16_as_u8_le(a.port()));
+            if b.len() < 7 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "buffer too small for v4 address",
+                ));
+            }
+            b[0] = 4;
+            b[1..5].copy_from_slice(&a.ip().octets());
+            b[5..7].copy_from_slice(&bytes::u16_as_u8_le(a.port()));
+            Ok(())
+        }
+        SocketAddr::V6(a) => {
+            if b.len() < 27 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "buffer too small for v6 address",
+                ));
+            }
+            b[0] = 6;
+            b[1..17].copy_from_slice(&a.ip().octets());
+            b[17..19].copy_from_slice(&bytes::u16_as_u8_le(a.port()));
+            b[19..23].copy_from_slice(&bytes::u32_as_u8_le(a.flowinfo()));
+            b[23..27].copy_from_slice(&bytes::u32_as_u8_le(a.scope_id()));
             Ok(())
         }
-        SocketAddr::V6(_) => Err(Error::new(ErrorKind::Other, "IPV6 not supported")),
+    }
+}
+
+// bytes_to_addr is the inverse of addr_to_bytes: it reads the leading family
+// byte and reconstructs the matching SocketAddr, length-checking the buffer
+// against the family so a short v6 buffer returns InvalidInput instead of
+// panicking on slice indexing.
+pub fn bytes_to_addr(b: &[u8]) -> Result<SocketAddr> {
+    if b.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "buffer too small for address family tag",
+        ));
+    }
+    match b[0] {
+        4 => {
+            if b.len() < 7 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "buffer too small for v4 address",
+                ));
+            }
+            let ip = Ipv4Addr::new(b[1], b[2], b[3], b[4]);
+            let port = u16::from_le_bytes([b[5], b[6]]);
+            Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+        }
+        6 => {
+            if b.len() < 27 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "buffer too small for v6 address",
+                ));
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&b[1..17]);
+            let port = u16::from_le_bytes([b[17], b[18]]);
+            let flowinfo = u32::from_le_bytes([b[19], b[20], b[21], b[22]]);
+            let scope_id = u32::from_le_bytes([b[23], b[24], b[25], b[26]]);
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(octets),
+                port,
+                flowinfo,
+                scope_id,
+            )))
+        }
+        other => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("unknown address family tag {}", other),
+        )),
     }
 }
 
 impl NetLoop {
     pub fn new() -> Self {
         let poll = Arc::new(mio::Poll::new().unwrap());
+        let (waker_registration, waker_set_readiness) = mio::Registration::new2();
+        poll.register(
+            &waker_registration,
+            mio::Token(WAKER_TOKEN),
+            mio::Ready::readable(),
+            mio::PollOpt::edge(),
+        )
+        .unwrap();
         let (event_sender, event_receiver) = mpsc::channel();
         let t_poll = poll.clone();
-        thread::spawn(move || {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let t_shutdown = shutdown.clone();
+        let thread_handle = thread::spawn(move || {
             let mut events = mio::Events::with_capacity(1024);
             loop {
                 t_poll.poll(&mut events, None).unwrap();
                 for event in events.iter() {
-                    event_sender.send(event).unwrap();
+                    if event_sender.send(event).is_err() {
+                        return;
+                    }
+                }
+                if t_shutdown.load(Ordering::Acquire) {
+                    return;
                 }
             }
         });
@@ -74,8 +190,32 @@ impl NetLoop {
             is_listening: false,
             poll,
             event_receiver,
+            waker_registration,
+            waker_set_readiness,
+            shutdown,
+            thread_handle: Some(thread_handle),
+            interests: HashMap::new(),
         }
     }
+    // wake makes the blocking poll() in the background thread return
+    // immediately, surfacing a synthetic event on WAKER_TOKEN so a caller
+    // parked in recv()/recv_timeout() wakes up and can re-check its own
+    // queues rather than waiting on a socket event that may never come.
+    pub fn wake(&self) -> Result<()> {
+        self.waker_set_readiness.set_readiness(mio::Ready::readable())
+    }
+    // shutdown_loop stops the background poll thread and joins it, instead
+    // of leaking it for the process lifetime.
+    pub fn shutdown_loop(&mut self) -> Result<()> {
+        self.shutdown.store(true, Ordering::Release);
+        self.wake()?;
+        if let Some(handle) = self.thread_handle.take() {
+            handle
+                .join()
+                .map_err(|_| Error::new(ErrorKind::Other, "poll thread panicked"))?;
+        }
+        Ok(())
+    }
     pub fn try_recv(&mut self) -> result::Result<mio::Event, mpsc::TryRecvError> {
         let event = self.event_receiver.try_recv()?;
         Ok(event)
@@ -93,14 +233,16 @@ impl NetLoop {
     }
     pub fn tcp_listen(&mut self, addr: &SocketAddr) -> Result<usize> {
         let listener = net::TcpListener::bind(addr)?;
-        let id = self.slab.insert(NetTcp::Listener(listener));
+        let id = self.slab.insert(NetSocket::Listener(listener));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
         self.poll.register(
             self.get_listener_ref(id)?,
             mio::Token(id),
-            mio::Ready::readable() | mio::Ready::writable(),
+            ready,
             // https://carllerche.github.io/mio/mio/struct.Poll.html#edge-triggered-and-level-triggered
             mio::PollOpt::edge(),
         )?;
+        self.interests.insert(id, ready);
         self.is_listening = true;
         Ok(id)
     }
@@ -110,13 +252,15 @@ impl NetLoop {
         self.register_stream(stream)
     }
     fn register_stream(&mut self, stream: mio::net::TcpStream) -> Result<usize> {
-        let id = self.slab.insert(NetTcp::Stream(stream));
+        let id = self.slab.insert(NetSocket::Stream(stream));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
         self.poll.register(
             self.get_stream_ref(id)?,
             mio::Token(id),
-            mio::Ready::readable() | mio::Ready::writable(),
+            ready,
             mio::PollOpt::edge(),
         )?;
+        self.interests.insert(id, ready);
         Ok(id)
     }
     pub fn tcp_accept(&mut self, id: usize) -> Result<usize> {
@@ -126,11 +270,111 @@ impl NetLoop {
         let (stream, _) = self.get_listener_ref(id)?.accept()?;
         self.register_stream(stream)
     }
+    pub fn udp_bind(&mut self, addr: &SocketAddr) -> Result<usize> {
+        let socket = net::UdpSocket::bind(addr)?;
+        self.register_datagram(socket)
+    }
+    pub fn udp_connect(&mut self, addr: &SocketAddr) -> Result<usize> {
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = net::UdpSocket::bind(&local)?;
+        socket.connect(*addr)?;
+        self.register_datagram(socket)
+    }
+    fn register_datagram(&mut self, socket: mio::net::UdpSocket) -> Result<usize> {
+        let id = self.slab.insert(NetSocket::Datagram(socket));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
+        self.poll.register(
+            self.get_datagram_ref(id)?,
+            mio::Token(id),
+            ready,
+            mio::PollOpt::edge(),
+        )?;
+        self.interests.insert(id, ready);
+        Ok(id)
+    }
+    // reregister recomputes the interest mask for `id` and re-arms the poll
+    // registration with it, keeping PollOpt::edge(). Callers use this to
+    // drop writable interest once their outbound buffer is flushed and
+    // re-arm it only when a write returns WouldBlock, avoiding the
+    // writable-event storm that comes from staying registered for
+    // readable() | writable() permanently.
+    pub fn reregister(&mut self, id: usize, readable: bool, writable: bool) -> Result<()> {
+        let mut ready = mio::Ready::empty();
+        if readable {
+            ready |= mio::Ready::readable();
+        }
+        if writable {
+            ready |= mio::Ready::writable();
+        }
+        match self.slab_get(id)? {
+            NetSocket::Listener(listener) => {
+                self.poll
+                    .reregister(listener, mio::Token(id), ready, mio::PollOpt::edge())?
+            }
+            NetSocket::Stream(stream) => {
+                self.poll
+                    .reregister(stream, mio::Token(id), ready, mio::PollOpt::edge())?
+            }
+            NetSocket::Datagram(socket) => {
+                self.poll
+                    .reregister(socket, mio::Token(id), ready, mio::PollOpt::edge())?
+            }
+            NetSocket::UnixListener(listener) => {
+                self.poll
+                    .reregister(listener, mio::Token(id), ready, mio::PollOpt::edge())?
+            }
+            NetSocket::UnixStream(stream) => {
+                self.poll
+                    .reregister(stream, mio::Token(id), ready, mio::PollOpt::edge())?
+            }
+            NetSocket::PendingSocket(_) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "socket is not registered with the poll loop yet",
+                ))
+            }
+        }
+        self.interests.insert(id, ready);
+        Ok(())
+    }
+    // get_interest mirrors event_to_ints's (token, state) bit layout —
+    // bit 0 readable, bit 1 writable — so the host can track per-token
+    // interest with the same decoding it already uses for events.
+    pub fn get_interest(&self, id: usize) -> Result<(i64, i64)> {
+        self.slab_get(id)?;
+        let ready = self
+            .interests
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| mio::Ready::readable() | mio::Ready::writable());
+        let state: i64 =
+            if ready.is_readable() { 1 } else { 0 } | if ready.is_writable() { 1 << 1 } else { 0 };
+        Ok((id as i64, state))
+    }
+    pub fn send_to(&self, id: usize, b: &[u8], addr: &SocketAddr) -> Result<usize> {
+        self.get_datagram_ref(id)?.send_to(b, addr)
+    }
+    pub fn recv_from(&self, id: usize, b: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.get_datagram_ref(id)?.recv_from(b)
+    }
+    pub fn send_dgram(&self, id: usize, b: &[u8]) -> Result<usize> {
+        self.get_datagram_ref(id)?.send(b)
+    }
+    pub fn recv_dgram(&self, id: usize, b: &mut [u8]) -> Result<usize> {
+        self.get_datagram_ref(id)?.recv(b)
+    }
     pub fn get_error(&mut self, id: usize) -> Result<Option<Error>> {
         match self.slab.get(id) {
-            Some(ntcp) => match ntcp {
-                NetTcp::Listener(listener) => listener.take_error(),
-                NetTcp::Stream(stream) => stream.take_error(),
+            Some(nsock) => match nsock {
+                NetSocket::Listener(listener) => listener.take_error(),
+                NetSocket::Stream(stream) => stream.take_error(),
+                NetSocket::Datagram(socket) => socket.take_error(),
+                NetSocket::PendingSocket(socket) => socket.take_error(),
+                NetSocket::UnixListener(listener) => listener.take_error(),
+                NetSocket::UnixStream(stream) => stream.take_error(),
             },
             None => Err(Error::new(
                 ErrorKind::Other,
@@ -140,34 +384,152 @@ impl NetLoop {
     }
     pub fn local_addr(&self, i: usize) -> Result<SocketAddr> {
         match self.slab_get(i)? {
-            NetTcp::Listener(listener) => listener.local_addr(),
-            NetTcp::Stream(stream) => stream.local_addr(),
+            NetSocket::Listener(listener) => listener.local_addr(),
+            NetSocket::Stream(stream) => stream.local_addr(),
+            NetSocket::Datagram(socket) => socket.local_addr(),
+            NetSocket::PendingSocket(socket) => sockaddr_to_std(socket.local_addr()?),
+            NetSocket::UnixListener(_) | NetSocket::UnixStream(_) => Err(Error::new(
+                ErrorKind::Other,
+                "unix domain sockets have no SocketAddr-shaped local address, use peer_path",
+            )),
         }
     }
     pub fn peer_addr(&self, i: usize) -> Result<SocketAddr> {
         self.get_stream_ref(i)?.peer_addr()
     }
     pub fn read_stream(&self, i: usize, b: &mut [u8]) -> Result<usize> {
-        if let Some(err) = self.get_stream_ref(i)?.take_error()? {
-            println!("stream error {:?}", err);
+        match self.slab_get(i)? {
+            NetSocket::Stream(s) => {
+                if let Some(err) = s.take_error()? {
+                    println!("stream error {:?}", err);
+                }
+                // `s` is bound by match ergonomics as `&TcpStream`; read()
+                // goes through `impl Read for &TcpStream`, which needs
+                // `&mut self`, so it must be rebound to a mutable local
+                // rather than called on the immutable match binding.
+                let mut s = s;
+                s.read(b)
+            }
+            NetSocket::UnixStream(s) => {
+                if let Some(err) = s.take_error()? {
+                    println!("stream error {:?}", err);
+                }
+                let mut s = s;
+                s.read(b)
+            }
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
         }
-        self.get_stream_ref(i)?.read(b)
     }
     pub fn shutdown(&mut self, i: usize, how: Shutdown) -> Result<()> {
-        self.get_stream_ref(i)?.shutdown(how)
+        match self.slab_get(i)? {
+            NetSocket::Stream(s) => s.shutdown(how),
+            NetSocket::UnixStream(s) => s.shutdown(how),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
     }
     pub fn write_stream(&self, i: usize, b: &[u8]) -> Result<usize> {
-        self.get_stream_ref(i)?.write(b)
+        match self.slab_get(i)? {
+            NetSocket::Stream(s) => {
+                let mut s = s;
+                s.write(b)
+            }
+            NetSocket::UnixStream(s) => {
+                let mut s = s;
+                s.write(b)
+            }
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
+    }
+    // read_vectored/write_vectored let a caller fill a header slab and a
+    // payload slab in a single readv/writev instead of copying framed
+    // messages into one contiguous buffer before every syscall. mio 0.6's
+    // TcpStream doesn't override Read/Write's read_vectored/write_vectored
+    // (they'd fall back to touching only the first buffer), so these call
+    // readv(2)/writev(2) directly on the stream's raw fd.
+    pub fn read_vectored(&self, i: usize, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        let stream = self.get_stream_ref(i)?;
+        if let Some(err) = stream.take_error()? {
+            println!("stream error {:?}", err);
+        }
+        read_vectored_via_raw_fd(stream.as_raw_fd(), bufs)
+    }
+    pub fn write_vectored(&self, i: usize, bufs: &[IoSlice]) -> Result<usize> {
+        write_vectored_via_raw_fd(self.get_stream_ref(i)?.as_raw_fd(), bufs)
+    }
+    // uds_listen/uds_connect/uds_accept give the host a zero-network-stack
+    // channel to local daemons on sandboxed targets where loopback TCP is
+    // restricted. They slot into the same slab and poll registration as
+    // TCP, so read_stream/write_stream/shutdown/close serve them unchanged.
+    pub fn uds_listen<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let listener = mio_uds::UnixListener::bind(path)?;
+        let id = self.slab.insert(NetSocket::UnixListener(listener));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
+        self.poll.register(
+            self.get_uds_listener_ref(id)?,
+            mio::Token(id),
+            ready,
+            mio::PollOpt::edge(),
+        )?;
+        self.interests.insert(id, ready);
+        self.is_listening = true;
+        Ok(id)
+    }
+    pub fn uds_connect<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let stream = mio_uds::UnixStream::connect(path)?;
+        self.register_uds_stream(stream)
+    }
+    fn register_uds_stream(&mut self, stream: mio_uds::UnixStream) -> Result<usize> {
+        let id = self.slab.insert(NetSocket::UnixStream(stream));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
+        self.poll.register(
+            self.get_uds_stream_ref(id)?,
+            mio::Token(id),
+            ready,
+            mio::PollOpt::edge(),
+        )?;
+        self.interests.insert(id, ready);
+        Ok(id)
+    }
+    pub fn uds_accept(&mut self, id: usize) -> Result<usize> {
+        match self.get_uds_listener_ref(id)?.accept()? {
+            Some((stream, _)) => self.register_uds_stream(stream),
+            None => Err(Error::new(
+                ErrorKind::WouldBlock,
+                "no pending unix domain connection",
+            )),
+        }
+    }
+    // peer_path stands in for peer_addr on unix domain peers, since
+    // addr_to_bytes/bytes_to_addr are inherently SocketAddr-shaped and a
+    // unix domain address is a filesystem path (or unnamed) instead.
+    pub fn peer_path(&self, i: usize) -> Result<Option<PathBuf>> {
+        match self.slab_get(i)? {
+            NetSocket::UnixStream(s) => Ok(s.peer_addr()?.as_pathname().map(|p| p.to_path_buf())),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
     }
     pub fn close(&mut self, i: usize) -> Result<()> {
         if self.slab.contains(i) {
             self.slab.remove(i); // value is dropped and connection is closed
         };
+        self.interests.remove(&i);
         Ok(())
     }
-    fn slab_get(&self, i: usize) -> Result<&NetTcp> {
+    fn slab_get(&self, i: usize) -> Result<&NetSocket> {
         match self.slab.get(i) {
-            Some(ntcp) => Ok(ntcp),
+            Some(nsock) => Ok(nsock),
             None => Err(Error::new(
                 ErrorKind::Other,
                 "Network object not found in slab",
@@ -176,7 +538,7 @@ impl NetLoop {
     }
     fn get_listener_ref(&self, i: usize) -> Result<&mio::net::TcpListener> {
         match self.slab_get(i)? {
-            NetTcp::Listener(listener) => Ok(listener),
+            NetSocket::Listener(listener) => Ok(listener),
             _ => Err(Error::new(
                 ErrorKind::Other,
                 "Network object not found in slab",
@@ -185,13 +547,225 @@ impl NetLoop {
     }
     fn get_stream_ref(&self, i: usize) -> Result<&mio::net::TcpStream> {
         match self.slab_get(i)? {
-            NetTcp::Stream(s) => Ok(s),
+            NetSocket::Stream(s) => Ok(s),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
+    }
+    fn get_datagram_ref(&self, i: usize) -> Result<&mio::net::UdpSocket> {
+        match self.slab_get(i)? {
+            NetSocket::Datagram(s) => Ok(s),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
+    }
+    fn get_pending_ref(&self, i: usize) -> Result<&socket2::Socket> {
+        match self.slab_get(i)? {
+            NetSocket::PendingSocket(s) => Ok(s),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
+    }
+    fn get_uds_listener_ref(&self, i: usize) -> Result<&mio_uds::UnixListener> {
+        match self.slab_get(i)? {
+            NetSocket::UnixListener(l) => Ok(l),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Network object not found in slab",
+            )),
+        }
+    }
+    fn get_uds_stream_ref(&self, i: usize) -> Result<&mio_uds::UnixStream> {
+        match self.slab_get(i)? {
+            NetSocket::UnixStream(s) => Ok(s),
             _ => Err(Error::new(
                 ErrorKind::Other,
                 "Network object not found in slab",
             )),
         }
     }
+    // take_pending removes the unbound socket at `id` from the slab so
+    // bind/listen/connect can consume it by value. The slab crate's free
+    // list is LIFO, so as long as nothing else touches the slab between
+    // this call and the following insert, the replacement entry lands back
+    // on the same id.
+    fn take_pending(&mut self, id: usize) -> Result<socket2::Socket> {
+        self.get_pending_ref(id)?;
+        match self.slab.remove(id) {
+            NetSocket::PendingSocket(socket) => Ok(socket),
+            other => {
+                self.slab.insert(other);
+                Err(Error::new(
+                    ErrorKind::Other,
+                    "socket is not a pending, unbound socket",
+                ))
+            }
+        }
+    }
+
+    // tcp_socket_v4/tcp_socket_v6 create an unbound socket and return its
+    // slab id so a caller can apply pre-bind options (set_reuseaddr,
+    // set_reuseport, set_nodelay, set_ttl) before bind()/listen() or
+    // connect() transitions it into a registered Listener/Stream.
+    pub fn tcp_socket_v4(&mut self) -> Result<usize> {
+        let socket = socket2::Socket::new(
+            socket2::Domain::ipv4(),
+            socket2::Type::stream(),
+            Some(socket2::Protocol::tcp()),
+        )?;
+        Ok(self.slab.insert(NetSocket::PendingSocket(socket)))
+    }
+    pub fn tcp_socket_v6(&mut self) -> Result<usize> {
+        let socket = socket2::Socket::new(
+            socket2::Domain::ipv6(),
+            socket2::Type::stream(),
+            Some(socket2::Protocol::tcp()),
+        )?;
+        Ok(self.slab.insert(NetSocket::PendingSocket(socket)))
+    }
+    pub fn set_reuseaddr(&mut self, id: usize, on: bool) -> Result<()> {
+        self.get_pending_ref(id)?.set_reuse_address(on)
+    }
+    // set_reuseport goes through a raw setsockopt(SO_REUSEPORT) rather than
+    // socket2::Socket::set_reuse_port, which is only compiled in behind
+    // that crate's own "reuseport" cargo feature - depending on libc
+    // directly here avoids a "method not found" surprise for anyone
+    // building with socket2's default features.
+    pub fn set_reuseport(&mut self, id: usize, on: bool) -> Result<()> {
+        set_reuse_port_via_raw_fd(self.get_pending_ref(id)?.as_raw_fd(), on)
+    }
+    pub fn set_nodelay(&mut self, id: usize, on: bool) -> Result<()> {
+        self.get_pending_ref(id)?.set_nodelay(on)
+    }
+    pub fn set_ttl(&mut self, id: usize, ttl: u32) -> Result<()> {
+        self.get_pending_ref(id)?.set_ttl(ttl)
+    }
+    pub fn get_localaddr(&self, id: usize) -> Result<SocketAddr> {
+        sockaddr_to_std(self.get_pending_ref(id)?.local_addr()?)
+    }
+    pub fn bind(&mut self, id: usize, addr: &SocketAddr) -> Result<()> {
+        self.get_pending_ref(id)?.bind(&(*addr).into())
+    }
+    pub fn listen(&mut self, id: usize, backlog: i32) -> Result<usize> {
+        let socket = self.take_pending(id)?;
+        socket.listen(backlog)?;
+        let std_listener: std::net::TcpListener = socket.into_tcp_listener();
+        let listener = net::TcpListener::from_std(std_listener)?;
+        let new_id = self.slab.insert(NetSocket::Listener(listener));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
+        self.poll.register(
+            self.get_listener_ref(new_id)?,
+            mio::Token(new_id),
+            ready,
+            mio::PollOpt::edge(),
+        )?;
+        self.interests.insert(new_id, ready);
+        self.is_listening = true;
+        Ok(new_id)
+    }
+    pub fn connect(&mut self, id: usize, addr: &SocketAddr) -> Result<usize> {
+        let socket = self.take_pending(id)?;
+        // Socket2's connect() is a blocking connect(2) unless the socket is
+        // already non-blocking, which would stall the caller's thread for
+        // the OS connect timeout against an unresponsive peer. Set
+        // non-blocking first, same as tcp_connect, and drive the handshake
+        // to completion through the poll loop's writable event instead.
+        socket.set_nonblocking(true)?;
+        match socket.connect(&(*addr).into()) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+            // A non-blocking connect(2) reports EINPROGRESS, which
+            // std::io::Error has no ErrorKind mapping for - check the raw
+            // errno directly, the same way socket2's own connect_timeout
+            // does internally.
+            Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {}
+            Err(e) => return Err(e),
+        }
+        let std_stream: std::net::TcpStream = socket.into_tcp_stream();
+        let stream = net::TcpStream::from_stream(std_stream)?;
+        let new_id = self.slab.insert(NetSocket::Stream(stream));
+        let ready = mio::Ready::readable() | mio::Ready::writable();
+        self.poll.register(
+            self.get_stream_ref(new_id)?,
+            mio::Token(new_id),
+            ready,
+            mio::PollOpt::edge(),
+        )?;
+        self.interests.insert(new_id, ready);
+        self.is_listening = true;
+        Ok(new_id)
+    }
+}
+
+// set_reuse_port_via_raw_fd sets SO_REUSEPORT directly through libc instead
+// of socket2::Socket::set_reuse_port, which is gated behind socket2's
+// "reuseport" cargo feature rather than enabled by default.
+fn set_reuse_port_via_raw_fd(fd: RawFd, on: bool) -> Result<()> {
+    let value: libc::c_int = if on { 1 } else { 0 };
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_REUSEPORT,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+// read_vectored_via_raw_fd/write_vectored_via_raw_fd call readv(2)/writev(2)
+// on `fd` directly through libc, rather than borrowing it as a second owning
+// std::net::TcpStream and mem::forget-ing it - an unwind between the borrow
+// and the forget would otherwise close the fd out from under the real
+// mio::net::TcpStream owner still sitting in the slab. std::io::IoSliceMut
+// and IoSlice are documented to share libc::iovec's layout on Unix, so the
+// pointer cast below is sound.
+fn read_vectored_via_raw_fd(fd: RawFd, bufs: &mut [IoSliceMut]) -> Result<usize> {
+    let ret = unsafe {
+        libc::readv(
+            fd,
+            bufs.as_ptr() as *const libc::iovec,
+            bufs.len() as libc::c_int,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(ret as usize)
+}
+fn write_vectored_via_raw_fd(fd: RawFd, bufs: &[IoSlice]) -> Result<usize> {
+    let ret = unsafe {
+        libc::writev(
+            fd,
+            bufs.as_ptr() as *const libc::iovec,
+            bufs.len() as libc::c_int,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(ret as usize)
+}
+
+// sockaddr_to_std converts a socket2::SockAddr obtained from a pre-bind
+// socket into the std::net::SocketAddr the rest of NetLoop's API speaks.
+fn sockaddr_to_std(addr: socket2::SockAddr) -> Result<SocketAddr> {
+    addr.as_std().ok_or_else(|| {
+        Error::new(
+            ErrorKind::Other,
+            "socket address family not supported",
+        )
+    })
 }
 
 #[cfg(test)]
@@ -203,14 +777,45 @@ mod tests {
     }
 
     #[test]
-    fn test_addr_to_bytes() {
-        let mut mem = vec![0u8; 6];
+    fn test_addr_to_bytes_v4() {
+        let mut mem = vec![0u8; 7];
         addr_to_bytes("1.2.3.4:100".parse().unwrap(), &mut mem).unwrap();
-        assert_eq!(mem, [1, 2, 3, 4, 100, 0]);
+        assert_eq!(mem, [4, 1, 2, 3, 4, 100, 0]);
 
-        let mut mem = vec![0u8; 6];
+        let mut mem = vec![0u8; 7];
         addr_to_bytes("127.0.0.1:34254".parse().unwrap(), &mut mem).unwrap();
-        assert_eq!(as_u16_le(&mem[4..6]), 34254u16);
+        assert_eq!(mem[0], 4);
+        assert_eq!(as_u16_le(&mem[5..7]), 34254u16);
+    }
+
+    #[test]
+    fn test_addr_to_bytes_v4_buffer_too_small() {
+        let mut mem = vec![0u8; 6];
+        let err = addr_to_bytes("1.2.3.4:100".parse().unwrap(), &mut mem).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_addr_to_bytes_v6_buffer_too_small() {
+        let mut mem = vec![0u8; 26];
+        let err = addr_to_bytes("[::1]:100".parse().unwrap(), &mut mem).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_addr_roundtrip_v4() {
+        let addr: SocketAddr = "127.0.0.1:34254".parse().unwrap();
+        let mut mem = vec![0u8; 7];
+        addr_to_bytes(addr, &mut mem).unwrap();
+        assert_eq!(bytes_to_addr(&mem).unwrap(), addr);
+    }
+
+    #[test]
+    fn test_addr_roundtrip_v6() {
+        let addr: SocketAddr = "[fe80::1%2]:34254".parse().unwrap();
+        let mut mem = vec![0u8; 27];
+        addr_to_bytes(addr, &mut mem).unwrap();
+        assert_eq!(bytes_to_addr(&mem).unwrap(), addr);
     }
 
     #[test]
@@ -237,4 +842,175 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn vectored_read_write_header_and_body() {
+        let mut nl = NetLoop::new();
+        let listener = nl.tcp_listen(&"127.0.0.1:34259".parse().unwrap()).unwrap();
+        let conn = nl.tcp_connect(&"127.0.0.1:34259".parse().unwrap()).unwrap();
+
+        let header = [1, 2, 3, 4];
+        let body = [5, 6, 7, 8, 9];
+        let mut accepted = None;
+        loop {
+            let event = nl.event_receiver.recv().unwrap();
+            if event.token().0 == conn && event.readiness().is_writable() {
+                let n = nl
+                    .write_vectored(conn, &[IoSlice::new(&header), IoSlice::new(&body)])
+                    .unwrap();
+                assert_eq!(n, header.len() + body.len());
+            } else if event.token().0 == listener && event.readiness().is_readable() {
+                accepted = Some(nl.tcp_accept(listener).unwrap());
+            } else if Some(event.token().0) == accepted && event.readiness().is_readable() {
+                let mut hbuf = [0; 4];
+                let mut bbuf = [0; 5];
+                let n = nl
+                    .read_vectored(
+                        accepted.unwrap(),
+                        &mut [IoSliceMut::new(&mut hbuf), IoSliceMut::new(&mut bbuf)],
+                    )
+                    .unwrap();
+                assert_eq!(n, header.len() + body.len());
+                assert_eq!(hbuf, header);
+                assert_eq!(bbuf, body);
+                break;
+            } else {
+                continue;
+            }
+        }
+    }
+
+    #[test]
+    fn wake_unblocks_recv() {
+        let mut nl = NetLoop::new();
+        nl.wake().unwrap();
+        let event = nl.recv().unwrap();
+        assert_eq!(event.token().0, WAKER_TOKEN);
+    }
+
+    #[test]
+    fn shutdown_loop_joins_thread() {
+        let mut nl = NetLoop::new();
+        nl.shutdown_loop().unwrap();
+        assert!(nl.thread_handle.is_none());
+    }
+
+    #[test]
+    fn reregister_drops_and_rearms_writable() {
+        let mut nl = NetLoop::new();
+        let listener = nl.tcp_listen(&"127.0.0.1:34257".parse().unwrap()).unwrap();
+        assert_eq!(nl.get_interest(listener).unwrap().1, 0b11);
+
+        nl.reregister(listener, true, false).unwrap();
+        assert_eq!(nl.get_interest(listener).unwrap().1, 0b01);
+
+        nl.reregister(listener, true, true).unwrap();
+        assert_eq!(nl.get_interest(listener).unwrap().1, 0b11);
+    }
+
+    #[test]
+    fn socket_builder_listen_applies_reuseaddr() {
+        let mut nl = NetLoop::new();
+        let id = nl.tcp_socket_v4().unwrap();
+        nl.set_reuseaddr(id, true).unwrap();
+        nl.set_nodelay(id, true).unwrap();
+        nl.bind(id, &"127.0.0.1:34258".parse().unwrap()).unwrap();
+        let listener = nl.listen(id, 128).unwrap();
+        assert_eq!(listener, id);
+        assert_eq!(
+            nl.local_addr(listener).unwrap(),
+            "127.0.0.1:34258".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn socket_builder_connect_is_nonblocking() {
+        let mut nl = NetLoop::new();
+        let listener = nl.tcp_listen(&"127.0.0.1:34261".parse().unwrap()).unwrap();
+
+        let id = nl.tcp_socket_v4().unwrap();
+        // Regression test for a non-blocking connect() whose connect(2)
+        // reports EINPROGRESS: this must return Ok immediately (handshake
+        // pending), not a hard error, the same way tcp_connect does.
+        let conn = nl.connect(id, &"127.0.0.1:34261".parse().unwrap()).unwrap();
+        assert_eq!(conn, id);
+
+        let to_write = [9, 8, 7];
+        let mut accepted = None;
+        loop {
+            let event = nl.event_receiver.recv().unwrap();
+            if event.token().0 == conn && event.readiness().is_writable() {
+                nl.write_stream(conn, &to_write).unwrap();
+            } else if event.token().0 == listener && event.readiness().is_readable() {
+                accepted = Some(nl.tcp_accept(listener).unwrap());
+            } else if Some(event.token().0) == accepted && event.readiness().is_readable() {
+                let mut b = [0; 3];
+                nl.read_stream(accepted.unwrap(), &mut b).unwrap();
+                assert_eq!(b, to_write);
+                break;
+            } else {
+                continue;
+            }
+        }
+    }
+
+    #[test]
+    fn uds_listen_connect_read_write() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("wasabi-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut nl = NetLoop::new();
+        let listener = nl.uds_listen(&path).unwrap();
+        let conn = nl.uds_connect(&path).unwrap();
+
+        let to_write = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+        let mut accepted = None;
+        loop {
+            let event = nl.event_receiver.recv().unwrap();
+            if event.token().0 == conn && event.readiness().is_writable() {
+                nl.write_stream(conn, &to_write).unwrap();
+            } else if event.token().0 == listener && event.readiness().is_readable() {
+                accepted = Some(nl.uds_accept(listener).unwrap());
+            } else if Some(event.token().0) == accepted && event.readiness().is_readable() {
+                let mut b = [0; 9];
+                nl.read_stream(accepted.unwrap(), &mut b).unwrap();
+                assert_eq!(b, to_write);
+                // `conn`'s peer is the listener's bound path; `accepted`'s
+                // peer is `conn`, which never bound a path of its own and
+                // so is unnamed from the server's side.
+                assert_eq!(nl.peer_path(conn).unwrap(), Some(path.clone()));
+                assert!(nl.peer_path(accepted.unwrap()).unwrap().is_none());
+                break;
+            } else {
+                continue;
+            }
+        }
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn udp_send_to_recv_from() {
+        let mut nl = NetLoop::new();
+        let server = nl.udp_bind(&"127.0.0.1:34255".parse().unwrap()).unwrap();
+        let client = nl.udp_bind(&"127.0.0.1:34256".parse().unwrap()).unwrap();
+        let server_addr = nl.local_addr(server).unwrap();
+
+        let to_write = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+        nl.send_to(client, &to_write, &server_addr).unwrap();
+
+        loop {
+            let event = nl.event_receiver.recv().unwrap();
+            if event.token().0 == server && event.readiness().is_readable() {
+                let mut b = [0; 9];
+                let (n, _) = nl.recv_from(server, &mut b).unwrap();
+                assert_eq!(n, to_write.len());
+                assert_eq!(b, to_write);
+                break;
+            } else {
+                continue;
+            }
+        }
+    }
 }